@@ -1,16 +1,17 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, sync::Arc, time::Duration};
 
 use eframe::App;
 use egui::epaint::RectShape;
 use egui::load::TexturePoll;
 use egui::{Color32, CornerRadius, Image, Pos2, Sense, Vec2, Widget, mutex::RwLock};
+use futures_util::StreamExt;
 use egui_taffy::{
     TuiBuilderLogic, tid, tui,
     virtual_tui::{VirtualGridRowHelper, VirtualGridRowHelperParams},
 };
 use shared::ImageResponse;
 use taffy::{
-    prelude::{auto, flex, length, percent, span},
+    prelude::{auto, flex, length, percent},
     style_helpers,
 };
 
@@ -48,6 +49,17 @@ impl Default for State {
 }
 
 impl State {
+    // Both the scroll-triggered fetch and the SSE stream can deliver the same image id
+    // more than once (e.g. a fast-ingest placeholder followed by its repaired version),
+    // so insertion always goes through here, replacing any existing entry in place.
+    fn insert_unique(&mut self, image: ImageResponse) {
+        if let Some(existing) = self.images.iter_mut().find(|existing| existing.id == image.id) {
+            *existing = image;
+        } else {
+            self.images.push(image);
+        }
+    }
+
     fn try_fetch(state: Arc<RwLock<Self>>, rt: &tokio::runtime::Runtime, ctx: egui::Context) {
         rt.spawn(async move {
             {
@@ -80,18 +92,125 @@ impl State {
             println!("fteched {} images", images.len());
             {
                 let mut state = state.write();
-                state.images.extend(images);
+                for image in images {
+                    state.insert_unique(image);
+                }
                 state.page += 1;
                 state.loading = false;
             }
             ctx.request_repaint();
         });
     }
+
+    fn spawn_event_listener(state: Arc<RwLock<Self>>, rt: &tokio::runtime::Runtime, ctx: egui::Context) {
+        rt.spawn(async move {
+            loop {
+                let response = match reqwest::get("http://localhost:3000/images/events").await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        tokio::time::sleep(Duration::from_secs(2)).await;
+                        continue;
+                    }
+                };
+
+                let mut stream = response.bytes_stream();
+                let mut buffer = String::new();
+
+                while let Some(chunk) = stream.next().await {
+                    let Ok(chunk) = chunk else { break };
+                    buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+                    while let Some(pos) = buffer.find("\n\n") {
+                        let event = buffer[..pos].to_string();
+                        buffer.drain(..pos + 2);
+
+                        for line in event.lines() {
+                            let Some(data) = line.strip_prefix("data: ") else {
+                                continue;
+                            };
+                            match serde_json::from_str::<ImageResponse>(data) {
+                                Ok(image) => {
+                                    state.write().insert_unique(image);
+                                    ctx.request_repaint();
+                                }
+                                Err(e) => eprintln!("{e}"),
+                            }
+                        }
+                    }
+                }
+
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+        });
+    }
+
+    fn spawn_upload(
+        state: Arc<RwLock<Self>>,
+        rt: &tokio::runtime::Runtime,
+        ctx: egui::Context,
+        files: Vec<egui::DroppedFile>,
+    ) {
+        rt.spawn(async move {
+            let mut form = reqwest::multipart::Form::new().text("author", "Local Upload");
+
+            for file in files {
+                let bytes = if let Some(bytes) = file.bytes.clone() {
+                    bytes.to_vec()
+                } else if let Some(path) = &file.path {
+                    match tokio::fs::read(path).await {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            eprintln!("{e}");
+                            continue;
+                        }
+                    }
+                } else {
+                    continue;
+                };
+
+                let name = if file.name.is_empty() {
+                    "upload".to_string()
+                } else {
+                    file.name.clone()
+                };
+                form = form.part("file", reqwest::multipart::Part::bytes(bytes).file_name(name));
+            }
+
+            let response = reqwest::Client::new()
+                .post("http://localhost:3000/images/upload")
+                .multipart(form)
+                .send()
+                .await;
+
+            let images = match response {
+                Ok(response) => response.json::<Vec<ImageResponse>>().await,
+                Err(e) => {
+                    eprintln!("{e}");
+                    return;
+                }
+            };
+
+            match images {
+                Ok(images) => {
+                    let mut state = state.write();
+                    for image in images {
+                        state.insert_unique(image);
+                    }
+                    ctx.request_repaint();
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        });
+    }
 }
 
 struct Gallery {
     rt: tokio::runtime::Runtime,
     state: Arc<RwLock<State>>,
+    // Justified-row packing is cached and recomputed only when the image count or the container
+    // width changes, not on every frame; an entry replaced in place keeps the cached layout.
+    justified_rows_cache: Option<(usize, f32, Vec<(f32, Vec<(usize, f32)>)>)>,
 }
 
 impl Gallery {
@@ -101,16 +220,28 @@ impl Gallery {
         cc.egui_ctx.all_styles_mut(|style| {
             style.wrap_mode = Some(egui::TextWrapMode::Extend);
         });
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let state = Arc::new(RwLock::new(State::default()));
+        State::spawn_event_listener(state.clone(), &rt, cc.egui_ctx.clone());
         Gallery {
-            rt: tokio::runtime::Runtime::new().unwrap(),
-            state: Arc::new(RwLock::new(State::default())),
+            rt,
+            state,
+            justified_rows_cache: None,
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum CoverFit {
+    #[default]
+    Cover,
+    Contain,
+}
+
 struct CoverImage<'a> {
     source: Cow<'a, str>,
     size: Vec2,
+    fit: CoverFit,
 }
 
 impl<'a> CoverImage<'a> {
@@ -118,8 +249,14 @@ impl<'a> CoverImage<'a> {
         Self {
             source: source.into(),
             size,
+            fit: CoverFit::default(),
         }
     }
+
+    fn fit(mut self, fit: CoverFit) -> Self {
+        self.fit = fit;
+        self
+    }
 }
 
 impl Widget for CoverImage<'_> {
@@ -148,31 +285,55 @@ impl Widget for CoverImage<'_> {
             Ok(TexturePoll::Ready { texture, .. }) => {
                 let tex_size = Vec2::new(texture.size[0] as f32, texture.size[1] as f32);
                 if tex_size.x > 0.0 && tex_size.y > 0.0 {
-                    let mut uv_min = Pos2::new(0.0, 0.0);
-                    let mut uv_max = Pos2::new(1.0, 1.0);
-
-                    let tex_ratio = tex_size.x / tex_size.y;
-                    let target_ratio = size.x / size.y;
-
-                    if target_ratio > tex_ratio {
-                        // Target is wider -> crop vertically
-                        let scale = target_ratio / tex_ratio;
-                        let visible = 1.0 / scale;
-                        let offset = (1.0 - visible) / 2.0;
-                        uv_min.y = offset;
-                        uv_max.y = 1.0 - offset;
-                    } else {
-                        // Target is taller -> crop horizontally
-                        let scale = tex_ratio / target_ratio;
-                        let visible = 1.0 / scale;
-                        let offset = (1.0 - visible) / 2.0;
-                        uv_min.x = offset;
-                        uv_max.x = 1.0 - offset;
-                    }
+                    match self.fit {
+                        CoverFit::Cover => {
+                            let mut uv_min = Pos2::new(0.0, 0.0);
+                            let mut uv_max = Pos2::new(1.0, 1.0);
+
+                            let tex_ratio = tex_size.x / tex_size.y;
+                            let target_ratio = size.x / size.y;
+
+                            if target_ratio > tex_ratio {
+                                // Target is wider -> crop vertically
+                                let scale = target_ratio / tex_ratio;
+                                let visible = 1.0 / scale;
+                                let offset = (1.0 - visible) / 2.0;
+                                uv_min.y = offset;
+                                uv_max.y = 1.0 - offset;
+                            } else {
+                                // Target is taller -> crop horizontally
+                                let scale = tex_ratio / target_ratio;
+                                let visible = 1.0 / scale;
+                                let offset = (1.0 - visible) / 2.0;
+                                uv_min.x = offset;
+                                uv_max.x = 1.0 - offset;
+                            }
+
+                            let shape = RectShape::filled(rect, rounding, Color32::WHITE)
+                                .with_texture(texture.id, egui::Rect::from_min_max(uv_min, uv_max));
+                            ui.painter().add(shape);
+                        }
+                        CoverFit::Contain => {
+                            // Scale to fit entirely inside rect, letterboxing any leftover space
+                            let tex_ratio = tex_size.x / tex_size.y;
+                            let target_ratio = size.x / size.y;
+
+                            let draw_size = if target_ratio > tex_ratio {
+                                Vec2::new(size.y * tex_ratio, size.y)
+                            } else {
+                                Vec2::new(size.x, size.x / tex_ratio)
+                            };
+                            let draw_rect = egui::Rect::from_center_size(rect.center(), draw_size);
 
-                    let shape = RectShape::filled(rect, rounding, Color32::WHITE)
-                        .with_texture(texture.id, egui::Rect::from_min_max(uv_min, uv_max));
-                    ui.painter().add(shape);
+                            ui.painter()
+                                .rect_filled(rect, rounding, Color32::from_gray(30));
+                            let shape = RectShape::filled(draw_rect, rounding, Color32::WHITE).with_texture(
+                                texture.id,
+                                egui::Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)),
+                            );
+                            ui.painter().add(shape);
+                        }
+                    }
                 } else {
                     ui.painter()
                         .rect_filled(rect, rounding, Color32::from_gray(30));
@@ -188,6 +349,53 @@ impl Widget for CoverImage<'_> {
     }
 }
 
+const JUSTIFIED_ROW_HEIGHT: f32 = 300.0;
+
+// Greedily packs images into rows by summing aspect ratios until the row's natural
+// width at `target_row_height` reaches `container_width`, then scales the row to fill
+// it exactly (Flickr-style justified gallery). Returns (row_height, [(image_index, width)]).
+fn compute_justified_rows(
+    images: &[ImageResponse],
+    container_width: f32,
+    target_row_height: f32,
+) -> Vec<(f32, Vec<(usize, f32)>)> {
+    let mut rows = Vec::new();
+    let mut current_row: Vec<(usize, f32)> = Vec::new();
+    let mut aspect_sum = 0.0f32;
+
+    for (index, image) in images.iter().enumerate() {
+        let aspect = if image.height > 0 {
+            image.width as f32 / image.height as f32
+        } else {
+            1.0
+        };
+
+        current_row.push((index, aspect));
+        aspect_sum += aspect;
+
+        if aspect_sum * target_row_height >= container_width {
+            let row_height = container_width / aspect_sum;
+            let widths = current_row
+                .iter()
+                .map(|&(i, a)| (i, a * row_height))
+                .collect();
+            rows.push((row_height, widths));
+            current_row.clear();
+            aspect_sum = 0.0;
+        }
+    }
+
+    if !current_row.is_empty() {
+        let widths = current_row
+            .iter()
+            .map(|&(i, a)| (i, a * target_row_height))
+            .collect();
+        rows.push((target_row_height, widths));
+    }
+
+    rows
+}
+
 impl App for Gallery {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let state = self.state.read();
@@ -195,8 +403,24 @@ impl App for Gallery {
             State::try_fetch(self.state.clone(), &self.rt, ctx.clone());
         }
 
+        let dropped_files = ctx.input(|i| i.raw.dropped_files.clone());
+        if !dropped_files.is_empty() {
+            State::spawn_upload(self.state.clone(), &self.rt, ctx.clone(), dropped_files);
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.label("Gallery");
+            let container_width = ui.available_width();
+            let image_count = state.images.len();
+            let cache_fresh = self
+                .justified_rows_cache
+                .as_ref()
+                .is_some_and(|(count, width, _)| *count == image_count && *width == container_width);
+            if !cache_fresh {
+                let rows = compute_justified_rows(&state.images, container_width, JUSTIFIED_ROW_HEIGHT);
+                self.justified_rows_cache = Some((image_count, container_width, rows));
+            }
+            let rows = &self.justified_rows_cache.as_ref().unwrap().2;
             tui(ui, ui.id().with("virtual_grid"))
                 .reserve_available_space()
                 .style(taffy::Style {
@@ -212,57 +436,67 @@ impl App for Gallery {
                             x: taffy::Overflow::Visible,
                             y: taffy::Overflow::Scroll,
                         },
-                        grid_template_columns: vec![flex(1.0), flex(1.0)],
+                        grid_template_columns: vec![flex(1.0)],
                         size: taffy::Size {
                             width: percent(100.),
                             height: auto(),
                         },
                         max_size: percent(1.),
-                        grid_auto_rows: vec![length(300.)],
+                        grid_auto_rows: vec![auto()],
                         ..Default::default()
                     })
                     .add(|tui| {
-                        let row_count = state.images.len() / 2;
+                        let row_count = rows.len();
                         VirtualGridRowHelper::show(
                             VirtualGridRowHelperParams {
                                 header_row_count: 1,
-                                row_count: row_count,
+                                row_count,
                             },
                             tui,
                             |tui, info| {
                                 let mut idgen = info.id_gen();
                                 let mut_grid_row_param = info.grid_row_setter();
+                                let (row_height, widths) = &rows[info.idx];
 
-                                for cidx in 0..2 {
-                                    let index = info.idx * 2 + cidx;
-                                    if index >= state.images.len() - 1 {
-                                        State::try_fetch(self.state.clone(), &self.rt, ctx.clone());
-                                    }
-                                    if index >= state.images.len() {
-                                        continue;
-                                    }
-                                    let image_response = &state.images[index];
-                                    tui.id(idgen())
-                                        .mut_style(&mut_grid_row_param)
-                                        .mut_style(|style| {
-                                            style.padding = length(2.);
-                                            style.size.width = percent(100.0);
-                                            style.size.height = length(300.0);
-                                        })
-                                        .ui(|ui| {
-                                            let mut size = ui.available_size();
-                                            if !size.y.is_finite() || size.y <= 0.0 {
-                                                size.y = 300.0;
-                                            } else {
-                                                size.y = 300.0;
-                                            }
-                                            let url = format!(
-                                                "http://localhost:3000/{}",
-                                                image_response.path
-                                            );
-                                            ui.add(CoverImage::new(url, size));
-                                        });
+                                if info.idx + 1 >= row_count {
+                                    State::try_fetch(self.state.clone(), &self.rt, ctx.clone());
                                 }
+
+                                tui.id(idgen())
+                                    .mut_style(&mut_grid_row_param)
+                                    .mut_style(|style| {
+                                        style.display = taffy::Display::Flex;
+                                        style.flex_direction = taffy::FlexDirection::Row;
+                                        style.size.width = percent(100.0);
+                                        style.size.height = length(*row_height);
+                                    })
+                                    .add(|tui| {
+                                        for &(image_index, width) in widths {
+                                            let image_response = &state.images[image_index];
+                                            tui.id(idgen())
+                                                .mut_style(|style| {
+                                                    style.padding = length(2.);
+                                                    style.size.width = length(width);
+                                                    style.size.height = length(*row_height);
+                                                })
+                                                .ui(|ui| {
+                                                    let size = Vec2::new(width, *row_height);
+                                                    let pixels_per_point = ui.ctx().pixels_per_point();
+                                                    let render_width =
+                                                        (size.x * pixels_per_point).round() as u32;
+                                                    let render_height =
+                                                        (size.y * pixels_per_point).round() as u32;
+                                                    let url = format!(
+                                                        "http://localhost:3000/images/{}/render?width={render_width}&height={render_height}&fit=contain",
+                                                        image_response.id
+                                                    );
+                                                    ui.add(
+                                                        CoverImage::new(url, size)
+                                                            .fit(CoverFit::Contain),
+                                                    );
+                                                });
+                                        }
+                                    });
                             },
                         );
 
@@ -272,7 +506,6 @@ impl App for Gallery {
                                 grid_row: style_helpers::line(1),
                                 padding: length(4.),
                                 align_items: Some(taffy::AlignItems::Center),
-                                grid_column: span(2),
                                 ..Default::default()
                             })
                             .id(tid(("header", 1)))
@@ -8,33 +8,29 @@ use std::{
 use anyhow::{Context, bail};
 use axum::{
     Router,
-    extract::{Query, State},
-    http::StatusCode,
-    response::Json,
+    extract::{Multipart, Path as AxumPath, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{any, get, post},
 };
-use futures_util::{TryStreamExt, future::join_all};
-use image::ImageReader;
-use serde::{Deserialize, Serialize};
+use futures_util::{Stream, StreamExt, TryStreamExt, future::join_all};
+use image::{ImageReader, imageops::FilterType};
+use serde::Deserialize;
+use shared::ImageResponse;
 use sqlx::{SqlitePool, sqlite::SqliteConnectOptions};
-use tokio::{fs::File, io::BufWriter, sync::Semaphore};
+use tokio::{fs::File, io::BufWriter, sync::Semaphore, sync::broadcast};
+use tokio_stream::wrappers::BroadcastStream;
 use tokio_util::io::{InspectReader, StreamReader};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: SqlitePool,
     pub data_dir: std::path::PathBuf,
-}
-
-#[derive(Serialize, Deserialize)]
-pub struct ImageResponse {
-    pub id: i64,
-    pub author: String,
-    pub width: i32,
-    pub height: i32,
-    pub hash: String,
-    pub path: String,
-    pub mime_type: String,
+    pub events: broadcast::Sender<ImageResponse>,
+    pub decode_semaphore: Arc<Semaphore>,
 }
 
 #[derive(Deserialize)]
@@ -43,6 +39,24 @@ pub struct FetchImagesQuery {
     pub limit: Option<u32>,
 }
 
+#[derive(Deserialize)]
+pub struct SimilarQuery {
+    pub id: Option<i64>,
+    pub max_distance: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct RenderQuery {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Option<String>,
+    pub format: Option<String>,
+}
+
+// Upper bound on a single requested render dimension, so large width/height query params
+// can't force an oversized resize + encode.
+const MAX_RENDER_DIMENSION: u32 = 4096;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize database
@@ -67,15 +81,27 @@ async fn main() -> anyhow::Result<()> {
     println!("Data directory: {:?}", data_dir);
 
     // Create app state
+    let (events_tx, _) = broadcast::channel(100);
     let app_state = AppState {
         db: pool,
         data_dir: data_dir.to_path_buf(),
+        events: events_tx,
+        decode_semaphore: Arc::new(Semaphore::new(5)),
     };
 
     // Build router
     let app = Router::new()
         .route("/images", get(get_images))
         .route("/images/fetch", any(fetch_and_insert_images))
+        .route(
+            "/images/similar",
+            get(similar_images).post(similar_images_upload),
+        )
+        .route("/images/{id}/render", get(render_image))
+        .route("/images/events", get(image_events))
+        .route("/images/upload", post(upload_images))
+        .route("/images/fetch/fast", any(fetch_and_insert_images_fast))
+        .route("/images/backfill", any(backfill_images))
         .with_state(app_state);
 
     // Start server
@@ -111,15 +137,262 @@ async fn get_images(
             author: row.author,
             width: row.width as i32,
             height: row.height as i32,
-            hash: hex::encode(row.hash),
+            hash: row.hash,
             path: row.path,
             mime_type: row.mime_type,
+            distance: None,
         })
         .collect();
 
     Ok(Json(response))
 }
 
+// GET /images/similar - Find images visually similar to an already-stored image
+async fn similar_images(
+    State(state): State<AppState>,
+    Query(params): Query<SimilarQuery>,
+) -> Result<Json<Vec<ImageResponse>>, StatusCode> {
+    let id = params.id.ok_or(StatusCode::BAD_REQUEST)?;
+    let max_distance = params.max_distance.unwrap_or(10);
+
+    let row = sqlx::query!("SELECT phash FROM images WHERE id = ?", id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let query_hash = row
+        .phash
+        .as_deref()
+        .and_then(phash_to_u64)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let matches = find_similar(&state.db, query_hash, max_distance, Some(id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(matches))
+}
+
+// POST /images/similar - Find images visually similar to an uploaded image
+async fn similar_images_upload(
+    State(state): State<AppState>,
+    Query(params): Query<SimilarQuery>,
+    mut multipart: Multipart,
+) -> Result<Json<Vec<ImageResponse>>, StatusCode> {
+    let max_distance = params.max_distance.unwrap_or(10);
+
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let bytes = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let hash = tokio::task::spawn_blocking(move || {
+        let image = ImageReader::new(io::Cursor::new(bytes))
+            .with_guessed_format()?
+            .decode()?;
+        anyhow::Ok(compute_dhash(&image))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let matches = find_similar(&state.db, u64::from_be_bytes(hash), max_distance, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(matches))
+}
+
+async fn find_similar(
+    db: &SqlitePool,
+    query_hash: u64,
+    max_distance: u32,
+    exclude_id: Option<i64>,
+) -> anyhow::Result<Vec<ImageResponse>> {
+    let rows = sqlx::query!(
+        "SELECT id, author, width, height, hash, phash, path, mime_type FROM images WHERE phash IS NOT NULL"
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut matches: Vec<ImageResponse> = rows
+        .into_iter()
+        .filter(|row| exclude_id != Some(row.id))
+        .filter_map(|row| {
+            let candidate_hash = phash_to_u64(row.phash.as_deref()?)?;
+            let distance = (query_hash ^ candidate_hash).count_ones();
+            (distance <= max_distance).then(|| ImageResponse {
+                id: row.id,
+                author: row.author,
+                width: row.width as i32,
+                height: row.height as i32,
+                hash: row.hash,
+                path: row.path,
+                mime_type: row.mime_type,
+                distance: Some(distance),
+            })
+        })
+        .collect();
+
+    matches.sort_by_key(|m| m.distance);
+
+    Ok(matches)
+}
+
+fn phash_to_u64(bytes: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+// GET /images/{id}/render - Resize/transcode a stored image on demand, caching the result on disk
+async fn render_image(
+    State(state): State<AppState>,
+    AxumPath(id): AxumPath<i64>,
+    Query(params): Query<RenderQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let row = sqlx::query!("SELECT path FROM images WHERE id = ?", id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let fit = params.fit.unwrap_or_else(|| "cover".to_string());
+    if !matches!(fit.as_str(), "cover" | "contain") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let format = params.format.unwrap_or_else(|| negotiate_format(&headers));
+    if !matches!(format.as_str(), "webp" | "jpeg" | "jpg" | "png") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Cap requested dimensions so a query like ?width=20000&height=20000 can't force an
+    // unbounded Lanczos resize and encode.
+    let width = params.width.map(|w| w.min(MAX_RENDER_DIMENSION));
+    let height = params.height.map(|h| h.min(MAX_RENDER_DIMENSION));
+
+    let cache_key = format!("{id}_{}x{}_{fit}.{format}", width.unwrap_or(0), height.unwrap_or(0));
+    let cache_path = state.data_dir.join("cache").join(&cache_key);
+
+    if let Ok(bytes) = tokio::fs::read(&cache_path).await {
+        return Ok(serve_rendered(bytes, &format));
+    }
+
+    let source_path = state.data_dir.join(&row.path);
+    let encode_format = format.clone();
+
+    let bytes = tokio::task::spawn_blocking(move || -> anyhow::Result<Vec<u8>> {
+        let image = ImageReader::open(&source_path)?
+            .with_guessed_format()?
+            .decode()?;
+        let resized = resize_for_fit(&image, width, height, &fit);
+
+        let image_format = match encode_format.as_str() {
+            "png" => image::ImageFormat::Png,
+            "jpeg" | "jpg" => image::ImageFormat::Jpeg,
+            _ => image::ImageFormat::WebP,
+        };
+
+        let mut bytes = Vec::new();
+        resized.write_to(&mut io::Cursor::new(&mut bytes), image_format)?;
+        Ok(bytes)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(parent) = cache_path.parent() {
+        let _ = tokio::fs::create_dir_all(parent).await;
+    }
+    let _ = tokio::fs::write(&cache_path, &bytes).await;
+
+    Ok(serve_rendered(bytes, &format))
+}
+
+fn resize_for_fit(
+    image: &image::DynamicImage,
+    width: Option<u32>,
+    height: Option<u32>,
+    fit: &str,
+) -> image::DynamicImage {
+    use image::GenericImageView;
+
+    let (image_width, image_height) = image.dimensions();
+    let (target_width, target_height) = match (width, height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, (w * image_height) / image_width.max(1)),
+        (None, Some(h)) => ((h * image_width) / image_height.max(1), h),
+        (None, None) => return image.clone(),
+    };
+
+    if fit == "contain" {
+        image.resize(
+            target_width.max(1),
+            target_height.max(1),
+            FilterType::Lanczos3,
+        )
+    } else {
+        image.resize_to_fill(
+            target_width.max(1),
+            target_height.max(1),
+            FilterType::Lanczos3,
+        )
+    }
+}
+
+fn negotiate_format(headers: &HeaderMap) -> String {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if accept.contains("image/webp") || accept.contains("*/*") || accept.is_empty() {
+        "webp".to_string()
+    } else {
+        "jpeg".to_string()
+    }
+}
+
+// GET /images/events - Server-sent events stream of newly inserted images and of pending rows once repaired
+async fn image_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let stream = BroadcastStream::new(state.events.subscribe())
+        .filter_map(|msg| async move { msg.ok() })
+        .map(|image| Event::default().json_data(image).map_err(axum::Error::new));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn serve_rendered(bytes: Vec<u8>, format: &str) -> Response {
+    let mime = match format {
+        "png" => "image/png",
+        "jpeg" | "jpg" => "image/jpeg",
+        _ => "image/webp",
+    };
+    ([(header::CONTENT_TYPE, mime)], bytes).into_response()
+}
+
+// Computes a 64-bit dHash: resize to 9x8 grayscale and compare each pixel to its right neighbour.
+fn compute_dhash(image: &image::DynamicImage) -> [u8; 8] {
+    let small = image
+        .grayscale()
+        .resize_exact(9, 8, FilterType::Lanczos3)
+        .to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8u32 {
+        for x in 0..8u32 {
+            let left = small.get_pixel(x, y)[0];
+            let right = small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | (left > right) as u64;
+        }
+    }
+    hash.to_be_bytes()
+}
+
 // POST /images/fetch - Fetch images from external API and insert into database
 async fn fetch_and_insert_images(
     State(state): State<AppState>,
@@ -132,7 +405,7 @@ async fn fetch_and_insert_images(
 
     let images_dir = state.data_dir.join("images");
 
-    let fetched_images = fetch_images(page, limit, &images_dir)
+    let fetched_images = fetch_images(page, limit, &images_dir, state.decode_semaphore.clone())
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -140,50 +413,382 @@ async fn fetch_and_insert_images(
 
     for image_result in fetched_images {
         match image_result {
-            Ok(image) => {
-                let w = image.width as i32;
-                let h = image.height as i32;
-                // Insert image into database
-                let result = sqlx::query!(
-                    r#"INSERT INTO images (author, width, height, hash, path, mime_type) VALUES (?, ?, ?, ?, ?, ?)"#,
-                    "Picsum Photos",
-                    w,h,
-                    image.hash,
-                    image.path,
-                    image.mime_type
-                )
-                .execute(&state.db)
+            Ok(image) => match insert_image(&state.db, "Picsum Photos", &image).await {
+                Ok(response) => {
+                    let _ = state.events.send(response.clone());
+                    inserted_images.push(response);
+                }
+                Err(e) => {
+                    eprintln!("Failed to insert image into database: {:?}", e);
+                }
+            },
+            Err(e) => {
+                eprintln!("Failed to fetch image: {:?}", e);
+            }
+        }
+    }
+
+    Ok(Json(inserted_images))
+}
+
+// Inserts a decoded image into the database, returning the response row
+async fn insert_image(
+    db: &SqlitePool,
+    author: &str,
+    image: &Image,
+) -> Result<ImageResponse, sqlx::Error> {
+    let w = image.width as i32;
+    let h = image.height as i32;
+    let hash = image.hash.clone();
+    let phash = image.phash.to_vec();
+
+    let result = sqlx::query!(
+        r#"INSERT INTO images (author, width, height, hash, phash, path, mime_type) VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+        author,
+        w,
+        h,
+        hash,
+        phash,
+        image.path,
+        image.mime_type
+    )
+    .execute(db)
+    .await?;
+
+    Ok(ImageResponse {
+        id: result.last_insert_rowid(),
+        author: author.to_string(),
+        width: image.width as i32,
+        height: image.height as i32,
+        hash: image.hash.clone(),
+        path: image.path.clone(),
+        mime_type: image.mime_type.clone(),
+        distance: None,
+    })
+}
+
+// POST /images/upload - Accept user-submitted images, decoding and storing them like the Picsum pipeline
+async fn upload_images(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<Vec<ImageResponse>>, StatusCode> {
+    let mut author = "Anonymous".to_string();
+    let mut uploaded_images = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        match field.name() {
+            Some("author") => {
+                author = field.text().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+            }
+            Some("file") => {
+                let bytes = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+                let semaphore = state.decode_semaphore.clone();
+                let filename = generate_random_filename();
+                let file_path = state.data_dir.join("images").join(&filename);
+
+                let image = tokio::spawn(async move {
+                    let lock = semaphore.acquire().await?;
+                    tokio::fs::write(&file_path, &bytes).await?;
+                    let image =
+                        tokio::task::spawn_blocking(move || decode_uploaded_image(&bytes, &filename))
+                            .await??;
+                    drop(lock);
+                    anyhow::Ok(image)
+                })
                 .await;
 
-                match result {
-                    Ok(query_result) => {
-                        let id = query_result.last_insert_rowid();
-                        inserted_images.push(ImageResponse {
-                            id,
-                            author: "Picsum Photos".to_string(),
-                            width: image.width as i32,
-                            height: image.height as i32,
-                            hash: hex::encode(&image.hash),
-                            path: image.path.clone(),
-                            mime_type: image.mime_type,
-                        });
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to insert image into database: {:?}", e);
-                    }
+                match image {
+                    Ok(Ok(image)) => match insert_image(&state.db, &author, &image).await {
+                        Ok(response) => {
+                            let _ = state.events.send(response.clone());
+                            uploaded_images.push(response);
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to insert uploaded image into database: {:?}", e);
+                        }
+                    },
+                    Ok(Err(e)) => eprintln!("Failed to decode uploaded image: {:?}", e),
+                    Err(e) => eprintln!("Upload task panicked: {:?}", e),
                 }
             }
+            _ => {}
+        }
+    }
+
+    Ok(Json(uploaded_images))
+}
+
+// Decodes an in-memory uploaded image and computes the same metadata as the fetch pipeline
+fn decode_uploaded_image(bytes: &[u8], filename: &str) -> anyhow::Result<Image> {
+    let reader = ImageReader::new(io::Cursor::new(bytes)).with_guessed_format()?;
+    let format = reader.format().context("couldn't determine format")?;
+    let mime_type = format.to_mime_type().to_string();
+    let image = reader.decode()?;
+
+    Ok(image_metadata(&image, mime_type, format!("images/{filename}")))
+}
+
+// Builds the stored Image record (thumbhash + dHash) from a decoded image
+fn image_metadata(image: &image::DynamicImage, mime_type: String, path: String) -> Image {
+    let width = image.width();
+    let height = image.height();
+    let thumbnail = image.thumbnail(100, 100);
+    let hash = thumbhash::rgba_to_thumb_hash(
+        thumbnail.width() as usize,
+        thumbnail.height() as usize,
+        &thumbnail.to_rgba8(),
+    );
+    let phash = compute_dhash(image);
+
+    Image {
+        width,
+        height,
+        hash,
+        phash,
+        path,
+        mime_type,
+    }
+}
+
+// POST /images/fetch/fast - List Picsum metadata and insert placeholder rows immediately,
+// then download, decode and repair each one in the background.
+async fn fetch_and_insert_images_fast(
+    State(state): State<AppState>,
+    Query(params): Query<FetchImagesQuery>,
+) -> Result<Json<Vec<ImageResponse>>, StatusCode> {
+    let page = params.page.unwrap_or(0);
+    let limit = params.limit.unwrap_or(10).min(50);
+
+    let listing = reqwest::get(format!(
+        "https://picsum.photos/v2/list?limit={limit}&page={page}"
+    ))
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .json::<Vec<PicsumImage>>()
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut inserted = Vec::new();
+
+    for entry in listing {
+        // Generate the destination path up front and persist it in the placeholder row
+        // itself: if the process crashes after the download has been written to disk but
+        // before the repair UPDATE runs, backfill_images still has a real path to re-decode.
+        let filename = generate_random_filename();
+        let path = format!("images/{filename}");
+
+        let result = sqlx::query!(
+            r#"INSERT INTO images (author, width, height, hash, path, mime_type, pending) VALUES (?, ?, ?, ?, ?, ?, 1)"#,
+            entry.author,
+            entry.width,
+            entry.height,
+            Vec::<u8>::new(),
+            path,
+            ""
+        )
+        .execute(&state.db)
+        .await;
+
+        let query_result = match result {
+            Ok(query_result) => query_result,
             Err(e) => {
-                eprintln!("Failed to fetch image: {:?}", e);
+                eprintln!("Failed to insert placeholder image: {:?}", e);
+                continue;
             }
+        };
+
+        let id = query_result.last_insert_rowid();
+        let response = ImageResponse {
+            id,
+            author: entry.author.clone(),
+            width: entry.width,
+            height: entry.height,
+            hash: Vec::new(),
+            path,
+            mime_type: String::new(),
+            distance: None,
+        };
+        let _ = state.events.send(response.clone());
+        inserted.push(response);
+
+        let state = state.clone();
+        let author = entry.author.clone();
+        let download_url = entry.download_url.clone();
+        tokio::spawn(async move {
+            if let Err(e) = fill_pending_image(&state, id, author, filename, download_url).await {
+                eprintln!("Failed to backfill pending image {id}: {:?}", e);
+            }
+        });
+    }
+
+    Ok(Json(inserted))
+}
+
+// Downloads and decodes a pending image, then repairs its row with the real dimensions,
+// hashes, path and MIME type.
+async fn fill_pending_image(
+    state: &AppState,
+    id: i64,
+    author: String,
+    filename: String,
+    download_url: String,
+) -> anyhow::Result<()> {
+    let file_path = state.data_dir.join("images").join(&filename);
+
+    let lock = state.decode_semaphore.acquire().await?;
+
+    let reader = reqwest::get(download_url)
+        .await?
+        .bytes_stream()
+        .map_err(std::io::Error::other);
+    let reader = StreamReader::new(reader);
+    let (sender, receiver) = mpsc::channel();
+    let mut reader = InspectReader::new(reader, |chunk| {
+        if let Err(e) = sender.send(chunk.to_vec()) {
+            eprintln!("{:?}", e);
+        }
+    });
+    let file = File::create(&file_path).await?;
+    let mut file = BufWriter::new(file);
+
+    let handle = tokio::task::spawn_blocking(move || {
+        let buffer = Buffer {
+            buffer: vec![],
+            position: 0,
+            channel: receiver,
+        };
+        let image = ImageReader::new(buffer).with_guessed_format()?;
+        let format = image.format().context("couldn't determine format")?;
+        let mime_type = format.to_mime_type().to_string();
+        let image = image.decode()?;
+        anyhow::Ok(image_metadata(&image, mime_type, format!("images/{filename}")))
+    });
+    tokio::io::copy(&mut reader, &mut file).await?;
+
+    let _ = sender.send(vec![]);
+    drop(lock);
+
+    let image = handle.await??;
+
+    let w = image.width as i32;
+    let h = image.height as i32;
+    let hash = image.hash.clone();
+    let phash = image.phash.to_vec();
+    let path = image.path.clone();
+    let mime_type = image.mime_type.clone();
+
+    sqlx::query!(
+        "UPDATE images SET width = ?, height = ?, hash = ?, phash = ?, path = ?, mime_type = ?, pending = 0 WHERE id = ?",
+        w,
+        h,
+        hash,
+        phash,
+        path,
+        mime_type,
+        id
+    )
+    .execute(&state.db)
+    .await?;
+
+    let response = ImageResponse {
+        id,
+        author,
+        width: w,
+        height: h,
+        hash: image.hash,
+        path: image.path,
+        mime_type: image.mime_type,
+        distance: None,
+    };
+    let _ = state.events.send(response);
+
+    Ok(())
+}
+
+// GET /images/backfill - Re-decode rows that are pending or have zero dimensions from their
+// stored file and repair the record, e.g. after a crash mid-batch. Rows whose file is missing,
+// and files that fail to decode, are logged and skipped. Returns the ids that were repaired.
+async fn backfill_images(State(state): State<AppState>) -> Result<Json<Vec<i64>>, StatusCode> {
+    let rows = sqlx::query!("SELECT id, width, height, path, pending FROM images")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut repaired = Vec::new();
+
+    for row in rows {
+        let file_exists = !row.path.is_empty() && state.data_dir.join(&row.path).exists();
+        let needs_repair =
+            row.pending != 0 || row.width == 0 || row.height == 0 || !file_exists;
+        if !needs_repair {
+            continue;
+        }
+        if !file_exists {
+            eprintln!(
+                "Backfill: no file to repair image {} from (path={:?}, pending={})",
+                row.id, row.path, row.pending
+            );
+            continue;
+        }
+
+        let full_path = state.data_dir.join(&row.path);
+        let relative_path = row.path.clone();
+        let result = tokio::task::spawn_blocking(move || -> anyhow::Result<Image> {
+            let reader = ImageReader::open(&full_path)?.with_guessed_format()?;
+            let format = reader.format().context("couldn't determine format")?;
+            let mime_type = format.to_mime_type().to_string();
+            let image = reader.decode()?;
+            Ok(image_metadata(&image, mime_type, relative_path))
+        })
+        .await;
+
+        let image = match result {
+            Ok(Ok(image)) => image,
+            Ok(Err(e)) => {
+                eprintln!("Backfill: failed to decode image {}: {:?}", row.id, e);
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Backfill: task panicked for image {}: {:?}", row.id, e);
+                continue;
+            }
+        };
+
+        let w = image.width as i32;
+        let h = image.height as i32;
+        let hash = image.hash.clone();
+        let phash = image.phash.to_vec();
+
+        let update = sqlx::query!(
+            "UPDATE images SET width = ?, height = ?, hash = ?, phash = ?, mime_type = ?, pending = 0 WHERE id = ?",
+            w,
+            h,
+            hash,
+            phash,
+            image.mime_type,
+            row.id
+        )
+        .execute(&state.db)
+        .await;
+
+        match update {
+            Ok(_) => repaired.push(row.id),
+            Err(e) => eprintln!("Backfill: failed to update image {}: {:?}", row.id, e),
         }
     }
 
-    Ok(Json(inserted_images))
+    Ok(Json(repaired))
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct PicsumImage {
+    author: String,
+    width: i32,
+    height: i32,
     download_url: String,
 }
 
@@ -191,6 +796,7 @@ async fn fetch_images(
     page: u32,
     limit: u32,
     path: &Path,
+    semaphore: Arc<Semaphore>,
 ) -> anyhow::Result<Vec<anyhow::Result<Image>>> {
     let images = reqwest::get(format!(
         "https://picsum.photos/v2/list?limit={limit}&page={page}"
@@ -199,8 +805,6 @@ async fn fetch_images(
     .json::<Vec<PicsumImage>>()
     .await?;
 
-    let semaphore = Arc::new(Semaphore::new(5));
-
     let images = images.into_iter().map(async |image| {
         let semaphore = semaphore.clone();
         let filename = generate_random_filename();
@@ -233,22 +837,7 @@ async fn fetch_images(
                 let mime_type = format.to_mime_type().to_string();
                 let image = image.decode()?;
 
-                let width = image.width();
-                let height = image.height();
-                let thumbnail = image.thumbnail(100, 100);
-                let hash = thumbhash::rgba_to_thumb_hash(
-                    thumbnail.width() as usize,
-                    thumbnail.height() as usize,
-                    &thumbnail.to_rgba8(),
-                );
-
-                anyhow::Ok(Image {
-                    width,
-                    height,
-                    hash,
-                    path: format!("images/{filename}"),
-                    mime_type,
-                })
+                anyhow::Ok(image_metadata(&image, mime_type, format!("images/{filename}")))
             });
             tokio::io::copy(&mut reader, &mut file).await?;
 
@@ -269,6 +858,7 @@ struct Image {
     width: u32,
     height: u32,
     hash: Vec<u8>,
+    phash: [u8; 8],
     path: String,
     mime_type: String,
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ImageResponse {
     pub id: i64,
     pub author: String,
@@ -10,6 +10,7 @@ pub struct ImageResponse {
     #[serde(deserialize_with = "hex::serde::deserialize")]
     pub hash: Vec<u8>,
     pub path: String,
-    pub url: String,
     pub mime_type: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub distance: Option<u32>,
 }